@@ -56,6 +56,7 @@ class LintCommand(Command):
                     "virtme/*.py",
                     "virtme/*/*.py",
                     "virtme_ng/*.py",
+                    "tests/*.py",
                 ):
                     command += glob(pattern)
                 check_call(command)
@@ -134,8 +135,20 @@ packages = [
 package_files = [
     "virtme-init",
     "virtme-udhcpc-script",
+    "virtme-dhcp-script",
     "virtme-snapd-script",
     "virtme-sound-script",
+    "virtme-sockets-script",
+    "virtme-rng-script",
+    "virtme-journal-script",
+    "virtme-notify-script",
+    "virtme-login-script",
+    "virtme-seccomp-script",
+    "virtme-vsock-script",
+    "virtme-qga-script",
+    "virtme-fsfreeze-script",
+    "virtme-reaper-script",
+    "virtme-uevent-script",
 ]
 
 if build_virtme_ng_init:
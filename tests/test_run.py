@@ -0,0 +1,123 @@
+import base64
+import io
+import os
+import tarfile
+import tempfile
+import unittest
+from unittest import mock
+
+from virtme.commands import run
+
+
+def make_tar(path, members):
+    with tarfile.open(path, "w") as tar:
+        for info in members:
+            data = None
+            if info.isfile():
+                data = io.BytesIO(b"data")
+                info.size = 4
+            tar.addfile(info, data)
+
+
+def tar_member(name, kind=tarfile.REGTYPE, linkname="", mode=0o644):
+    info = tarfile.TarInfo(name)
+    info.type = kind
+    info.linkname = linkname
+    info.mode = mode
+    return info
+
+
+class EnvKernelArgsTest(unittest.TestCase):
+    def test_values_are_base64_encoded(self):
+        args = run.env_kernel_args(["FOO=bar baz", "EMPTY="])
+        self.assertEqual(args, [
+            "virtme_env_FOO=" + base64.b64encode(b"bar baz").decode(),
+            "virtme_env_EMPTY=",
+            "virtme_env=FOO,EMPTY",
+        ])
+
+    def test_value_from_host(self):
+        with mock.patch.dict(os.environ, {"VIRTME_TEST_VAR": "a=b"}):
+            args = run.env_kernel_args(["VIRTME_TEST_VAR"])
+        self.assertEqual(args[0], "virtme_env_VIRTME_TEST_VAR=" + base64.b64encode(b"a=b").decode())
+
+    def test_no_env(self):
+        self.assertEqual(run.env_kernel_args([]), [])
+
+
+class BashInitOptionsTest(unittest.TestCase):
+    def parse(self, argv):
+        return run.make_parser().parse_args(argv)
+
+    def test_basic_options(self):
+        self.assertEqual(run.bash_init_options(self.parse(["--kopt", "virtme.dhcp", "--kopt", "quiet"])), [])
+
+    def test_host_options(self):
+        args = self.parse(["--env", "A=1", "--kmsg", "kmsg.log", "--push", "dir"])
+        self.assertEqual(run.bash_init_options(args), ["--env", "--kmsg", "--push"])
+
+    def test_guest_parameters(self):
+        args = self.parse([
+            "--kopt", "virtme.seccomp=profile",
+            "--kopt", "virtme_dns=1.1.1.1",
+            "--kopt", "virtme_rw_overlay0=/etc",
+            "--kopt", "virtme_hostname=test",
+        ])
+        self.assertEqual(run.bash_init_options(args), ["virtme.seccomp", "virtme_dns"])
+
+
+class FetchArtifactsTest(unittest.TestCase):
+    def setUp(self):
+        self.tmpdir = tempfile.TemporaryDirectory()
+        self.archive = os.path.join(self.tmpdir.name, "artifacts.tar")
+        self.dest = os.path.join(self.tmpdir.name, "dest")
+
+    def tearDown(self):
+        self.tmpdir.cleanup()
+
+    def fetch(self, members, data_filter):
+        make_tar(self.archive, members)
+        if data_filter:
+            run.fetch_artifacts(self.archive, self.dest)
+            return
+        # Exercise the checks used without the "data" extraction filter
+        saved = tarfile.__dict__.pop("data_filter", None)
+        try:
+            run.fetch_artifacts(self.archive, self.dest)
+        finally:
+            if saved is not None:
+                tarfile.data_filter = saved
+
+    def test_regular_files(self):
+        for data_filter in (True, False):
+            with self.subTest(data_filter=data_filter):
+                self.fetch([tar_member("./logs", tarfile.DIRTYPE, mode=0o755),
+                            tar_member("./logs/test.log", mode=0o4777)], data_filter)
+                path = os.path.join(self.dest, "logs", "test.log")
+                with open(path, encoding="utf-8") as log:
+                    self.assertEqual(log.read(), "data")
+                self.assertFalse(os.stat(path).st_mode & 0o4022)
+
+    def test_rejected_members(self):
+        members = {
+            "parent directory": tar_member("../escape"),
+            "absolute path": tar_member(os.path.join(self.tmpdir.name, "escape")),
+            "symlink": tar_member("link", tarfile.SYMTYPE, linkname="/etc/passwd"),
+            "hard link": tar_member("hardlink", tarfile.LNKTYPE, linkname="/etc/passwd"),
+            "device": tar_member("null", tarfile.CHRTYPE),
+        }
+        for name, member in members.items():
+            with self.subTest(member=name), mock.patch("sys.stderr", new_callable=io.StringIO) as stderr:
+                self.fetch([member], data_filter=False)
+                self.assertIn("failed to retrieve the artifacts", stderr.getvalue())
+                self.assertEqual(os.listdir(self.dest), [])
+                self.assertFalse(os.path.exists(os.path.join(self.tmpdir.name, "escape")))
+
+    def test_empty_archive(self):
+        open(self.archive, "wb").close()
+        run.fetch_artifacts(self.archive, self.dest)
+        self.assertFalse(os.path.exists(self.dest))
+
+
+if __name__ == "__main__":
+    unittest.main()
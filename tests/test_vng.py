@@ -0,0 +1,55 @@
+import unittest
+from unittest import mock
+
+from virtme_ng import run
+
+
+class VirtmeParamTest(unittest.TestCase):
+    """Check the virtme-run options generated from the vng options."""
+
+    # pylint: disable=protected-access
+
+    def setUp(self):
+        with mock.patch.object(run.KernelSource, "get_conf_file_path", return_value=None):
+            self.kernel_source = run.KernelSource()
+
+    def params(self, argv):
+        args = run.make_parser().parse_args(argv)
+        for method in (
+            "_get_virtme_env",
+            "_get_virtme_result",
+            "_get_virtme_control",
+            "_get_virtme_artifacts",
+            "_get_virtme_push",
+            "_get_virtme_rng",
+            "_get_virtme_vsock_cid",
+        ):
+            getattr(self.kernel_source, method)(args)
+        return self.kernel_source.virtme_param
+
+    def test_defaults(self):
+        params = self.params([])
+        for name in ("env", "result", "control", "artifacts", "push", "rng", "vsock_cid"):
+            self.assertEqual(params[name], "", name)
+
+    def test_env(self):
+        self.assertEqual(self.params(["--env", "A=1", "--env", "B"])["env"], '--env "A=1" --env "B" ')
+
+    def test_scripting_channels(self):
+        params = self.params(["--result", "result.json", "--control", "control.sock"])
+        self.assertEqual(params["result"], "--result result.json")
+        self.assertEqual(params["control"], "--control control.sock")
+
+    def test_file_transfers(self):
+        params = self.params(["--artifacts", "out", "--push", "in"])
+        self.assertEqual(params["artifacts"], "--artifacts out")
+        self.assertEqual(params["push"], "--push in")
+
+    def test_devices(self):
+        params = self.params(["--rng", "--vsock-cid", "42"])
+        self.assertEqual(params["rng"], "--rng")
+        self.assertEqual(params["vsock_cid"], "--vsock-cid 42")
+
+
+if __name__ == "__main__":
+    unittest.main()
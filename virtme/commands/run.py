@@ -19,8 +19,11 @@ import re
 import itertools
 import subprocess
 import signal
+import socket
+import tarfile
 import termios
-from shutil import which
+import threading
+from shutil import which, rmtree
 from time import sleep
 from base64 import b64encode
 from .. import virtmods
@@ -112,6 +115,11 @@ def make_parser() -> argparse.ArgumentParser:
         help="Show graphical output instead of using a console. "
         + "An argument can be optionally specified to start a graphical application.",
     )
+    g.add_argument(
+        "--clipboard",
+        action="store_true",
+        help="Share the clipboard with the guest in graphics mode (requires spice-vdagent in the guest)",
+    )
     g.add_argument(
         "--verbose", action="store_true", help="Increase console output verbosity."
     )
@@ -133,6 +141,19 @@ def make_parser() -> argparse.ArgumentParser:
         action="store_true",
         help="Enable audio device (if the architecture supports it).",
     )
+    g.add_argument(
+        "--vsock-cid",
+        action="store",
+        type=int,
+        metavar="CID",
+        help="Add a vsock device with the context ID CID (>= 3), used by the guest vsock services "
+        + "(virtme.vsock_console, virtme.agent, virtme.forward, ...).",
+    )
+    g.add_argument(
+        "--rng",
+        action="store_true",
+        help="Add a virtio-rng device, fed by the host /dev/urandom, to seed the guest RNG early.",
+    )
     g.add_argument(
         "--snaps", action="store_true", help="Allow to execute snaps inside virtme-ng"
     )
@@ -172,6 +193,13 @@ def make_parser() -> argparse.ArgumentParser:
         help="Set guest hostname and qemu -name flag.",
     )
     g.add_argument("--user", action="store", help="Change guest user")
+    g.add_argument(
+        "--env",
+        action="append",
+        default=[],
+        metavar="NAME[=VALUE]",
+        help="Pass an environment variable to the guest (the value is taken from the host if not specified).",
+    )
 
     g = parser.add_argument_group(
         title="Scripting",
@@ -264,6 +292,60 @@ def make_parser() -> argparse.ArgumentParser:
         action="store",
         help="Save the generated initramfs to the specified path",
     )
+    g.add_argument(
+        "--result",
+        action="store",
+        metavar="PATH",
+        help="Write a JSON report of the script execution (exit code, timings, resource usage) to PATH",
+    )
+    g.add_argument(
+        "--control",
+        action="store",
+        metavar="PATH",
+        help="Create a control socket at PATH to send commands to the guest (rerun, freeze, thaw, poweroff)",
+    )
+    g.add_argument(
+        "--notify",
+        action="store",
+        metavar="PATH",
+        help="Write the readiness notifications (sd_notify) of the guest to PATH",
+    )
+    g.add_argument(
+        "--script-stdout",
+        action="store",
+        metavar="PATH",
+        help="Write the standard output of the script to PATH instead of stdout",
+    )
+    g.add_argument(
+        "--script-stderr",
+        action="store",
+        metavar="PATH",
+        help="Write the standard error of the script to PATH instead of stderr",
+    )
+    g.add_argument(
+        "--kmsg",
+        action="store",
+        metavar="PATH",
+        help="Write the kernel log records of the guest (/dev/kmsg format) to PATH",
+    )
+    g.add_argument(
+        "--qga",
+        action="store",
+        metavar="PATH",
+        help="Create a QEMU guest agent socket at PATH (org.qemu.guest_agent.0 channel)",
+    )
+    g.add_argument(
+        "--artifacts",
+        action="store",
+        metavar="DIR",
+        help="Retrieve the content of the guest workspace ($VIRTME_WORKSPACE) into DIR at shutdown",
+    )
+    g.add_argument(
+        "--push",
+        action="store",
+        metavar="PATH",
+        help="Copy the file or directory PATH into the guest workspace ($VIRTME_WORKSPACE) at boot",
+    )
     g.add_argument(
         "--show-boot-console",
         action="store_true",
@@ -272,7 +354,9 @@ def make_parser() -> argparse.ArgumentParser:
     g.add_argument(
         "--no-virtme-ng-init",
         action="store_true",
-        help="Fallback to the bash virtme-init (useful for debugging/development)",
+        help="Fallback to the bash virtme-init (useful for debugging/development). "
+        + "The bash virtme-init is always used with the options that only it supports "
+        + "(e.g., --env, --result, --control, --notify, --artifacts, --push, virtme.* guest parameters)",
     )
 
     g = parser.add_argument_group(
@@ -759,6 +843,70 @@ def can_use_microvm(args):
     return not args.disable_microvm and not args.numa and args.arch == "x86_64" and can_use_kvm(args)
 
 
+# Options implemented only by the bash virtme-init: virtme-ng-init would ignore
+# them, so they force the use of virtme-init.
+BASH_INIT_OPTIONS = [
+    "env",
+    "clipboard",
+    "result",
+    "control",
+    "notify",
+    "script_stdout",
+    "script_stderr",
+    "kmsg",
+    "qga",
+    "artifacts",
+    "push",
+]
+
+# Guest parameters (kernel options) supported by virtme-ng-init as well
+NG_INIT_PARAMS = [
+    "virtme.exec",
+    "virtme.dhcp",
+    "virtme.snapd",
+    "virtme.sound",
+    "virtme_hostname",
+    "virtme_chdir",
+    "virtme_user",
+    "virtme_root_user",
+    "virtme_console",
+    "virtme_stty_con",
+    "virtme_graphics",
+    "virtme_root_mods",
+    "virtme_link_mods",
+]
+
+
+def bash_init_options(args) -> List[str]:
+    """Return the options in use that only virtme-init supports."""
+    options = ["--" + name.replace("_", "-") for name in BASH_INIT_OPTIONS if getattr(args, name)]
+    for kopt in args.kopt:
+        name = kopt.split("=", 1)[0]
+        if not name.startswith(("virtme.", "virtme_")) or name in NG_INIT_PARAMS:
+            continue
+        if re.fullmatch(r"virtme_(rw_overlay|initmount)\d+", name):
+            continue
+        options.append(name)
+    return options
+
+
+def env_kernel_args(envs: List[str]) -> List[str]:
+    # Pass the requested environment variables (NAME=VALUE, or NAME to take the
+    # value from the host) base64-encoded, so that their values can contain any
+    # character.
+    kernelargs = []
+    env_names = []
+    for env in envs:
+        name, _, value = env.partition("=")
+        if "=" not in env:
+            value = os.environ.get(name, "")
+        env_names.append(name)
+        kernelargs.append("virtme_env_%s=%s" % (name, b64encode(value.encode()).decode()))
+    if env_names:
+        kernelargs.append("virtme_env=%s" % ",".join(env_names))
+    return kernelargs
+
+
 def has_read_acl(username, file_path):
     try:
         # Execute the `getfacl` command and capture the output
@@ -941,13 +1089,22 @@ def do_it() -> int:
             qemu, arch, qemuargs, args.root, "/dev/root", readonly=(not args.rw)
         )
 
-    # Use the faster virtme-ng-init if we are running on a native architecture.
+    # Use the faster virtme-ng-init if we are running on a native architecture,
+    # unless an option that only virtme-init supports is used.
+    bash_init_only = bash_init_options(args)
     if (
         is_native
         and not args.no_virtme_ng_init
         and os.path.exists(guest_tools_path + "/bin/virtme-ng-init")
     ):
-        virtme_init_cmd = "bin/virtme-ng-init"
+        if bash_init_only:
+            if args.verbose:
+                sys.stderr.write(
+                    "virtme: using virtme-init, required by %s\n" % ", ".join(bash_init_only)
+                )
+            virtme_init_cmd = "virtme-init"
+        else:
+            virtme_init_cmd = "bin/virtme-ng-init"
     else:
         virtme_init_cmd = "virtme-init"
 
@@ -1103,6 +1260,70 @@ def do_it() -> int:
     if args.balloon:
         qemuargs.extend(["-device", "%s,id=balloon0" % arch.virtio_dev_type("balloon")])
 
+    if args.vsock_cid is not None:
+        if args.vsock_cid < 3:
+            arg_fail("--vsock-cid: the context ID must be >= 3")
+        if not can_access_file("/dev/vhost-vsock"):
+            arg_fail("--vsock-cid requires read/write access to /dev/vhost-vsock (modprobe vhost_vsock)")
+        vsock_dev = arch.virtio_dev_type("vsock").replace("virtio-", "vhost-", 1)
+        qemuargs.extend(["-device", f"{vsock_dev},guest-cid={args.vsock_cid}"])
+
+    if args.rng:
+        qemuargs.extend(["-object", "rng-random,id=rng0,filename=/dev/urandom"])
+        qemuargs.extend(["-device", "%s,rng=rng0" % arch.virtio_dev_type("rng")])
+
+    if args.result:
+        # Set up a virtserialport to receive the report of the script (JSON)
+        qemuargs.extend(["-chardev", f"file,id=result,path={args.result}"])
+        qemuargs.extend(["-device", arch.virtio_dev_type("serial")])
+        qemuargs.extend(["-device", "virtserialport,name=virtme.result,chardev=result"])
+
+    if args.control:
+        # Set up a virtserialport to send commands to the guest (e.g., rerun)
+        qemuargs.extend(["-chardev", f"socket,id=control,path={args.control},server=on,wait=off"])
+        qemuargs.extend(["-device", arch.virtio_dev_type("serial")])
+        qemuargs.extend(["-device", "virtserialport,name=virtme.control,chardev=control"])
+
+    if args.notify:
+        # Set up a virtserialport to receive the readiness notifications
+        qemuargs.extend(["-chardev", f"file,id=notify,path={args.notify}"])
+        qemuargs.extend(["-device", arch.virtio_dev_type("serial")])
+        qemuargs.extend(["-device", "virtserialport,name=virtme.notify,chardev=notify"])
+
+    if args.qga:
+        # Set up the channel of the QEMU guest agent
+        qemuargs.extend(["-chardev", f"socket,id=qga,path={args.qga},server=on,wait=off"])
+        qemuargs.extend(["-device", arch.virtio_dev_type("serial")])
+        qemuargs.extend(["-device", "virtserialport,name=org.qemu.guest_agent.0,chardev=qga"])
+
+    artifacts_path = None
+    if args.artifacts:
+        # Set up a virtserialport to receive the workspace of the guest (tar)
+        fd, artifacts_path = tempfile.mkstemp(prefix="virtme_artifacts")
+        os.close(fd)
+        atexit.register(os.unlink, artifacts_path)
+        qemuargs.extend(["-chardev", f"file,id=artifacts,path={artifacts_path}"])
+        qemuargs.extend(["-device", arch.virtio_dev_type("serial")])
+        qemuargs.extend(["-device", "virtserialport,name=virtme.artifacts,chardev=artifacts"])
+
+    push_path = None
+    if args.push:
+        # Set up a virtserialport to send PATH to the guest workspace (tar)
+        if not os.path.exists(args.push):
+            arg_fail(f"--push: {args.push} does not exist")
+        push_dir = tempfile.mkdtemp(prefix="virtme_push")
+        atexit.register(rmtree, push_dir, ignore_errors=True)
+        push_path = os.path.join(push_dir, "sock")
+        qemuargs.extend(["-chardev", f"socket,id=push,path={push_path},server=on,wait=off"])
+        qemuargs.extend(["-device", arch.virtio_dev_type("serial")])
+        qemuargs.extend(["-device", "virtserialport,name=virtme.push,chardev=push"])
+
+    if args.kmsg:
+        # Set up a virtserialport to receive the kernel log of the guest
+        qemuargs.extend(["-chardev", f"file,id=kmsg,path={args.kmsg}"])
+        qemuargs.extend(["-device", arch.virtio_dev_type("serial")])
+        qemuargs.extend(["-device", "virtserialport,name=virtme.kmsg,chardev=kmsg"])
+
     if args.cpus:
         qemuargs.extend(["-smp", args.cpus])
 
@@ -1137,23 +1358,35 @@ def do_it() -> int:
             )
 
     ret_path = None
+    winsize_path = None
 
     def cleanup_script_retcode():
         os.unlink(ret_path)
 
+    def cleanup_winsize():
+        if os.path.exists(winsize_path):
+            os.unlink(winsize_path)
+        os.rmdir(os.path.dirname(winsize_path))
+
     def fetch_script_retcode():
         if ret_path is None:
             return None
         try:
             with open(ret_path, 'r', encoding="utf-8") as file:
-                number_str = file.read().strip()
-                if number_str.isdigit():
-                    return int(number_str)
+                # The first line contains the exit code, the second one (if
+                # any) the exit codes of the individual scripts.
+                lines = file.read().strip().splitlines()
+                if len(lines) > 1 and args.verbose:
+                    sys.stderr.write(f"virtme: scripts exit codes: {lines[1]}\n")
+                if lines and lines[0].isdigit():
+                    return int(lines[0])
                 return None
         except FileNotFoundError:
             return None
 
     def do_script(shellcmd: str, ret_path=None, show_boot_console=False) -> None:
+        nonlocal winsize_path
+
         if args.graphics is None:
             # Turn off default I/O
             qemuargs.extend(arch.qemu_nodisplay_args())
@@ -1195,21 +1428,33 @@ def do_it() -> int:
         qemuargs.extend(["-device", arch.virtio_dev_type("serial")])
         qemuargs.extend(["-device", "virtserialport,name=virtme.stdin,chardev=stdin"])
 
-        qemuargs.extend(["-chardev", "file,id=stdout,path=/proc/self/fd/1"])
+        # The output of the script can be redirected to separate files: both
+        # the ports of each stream are opened in append mode, since they
+        # share the same file.
+        stdout_chardev = "path=/proc/self/fd/1"
+        if args.script_stdout:
+            open(args.script_stdout, "w", encoding="utf-8").close()
+            stdout_chardev = f"path={args.script_stdout},append=on"
+        stderr_chardev = "path=/proc/self/fd/2"
+        if args.script_stderr:
+            open(args.script_stderr, "w", encoding="utf-8").close()
+            stderr_chardev = f"path={args.script_stderr},append=on"
+
+        qemuargs.extend(["-chardev", f"file,id=stdout,{stdout_chardev}"])
         qemuargs.extend(["-device", arch.virtio_dev_type("serial")])
         qemuargs.extend(["-device", "virtserialport,name=virtme.stdout,chardev=stdout"])
 
-        qemuargs.extend(["-chardev", "file,id=stderr,path=/proc/self/fd/2"])
+        qemuargs.extend(["-chardev", f"file,id=stderr,{stderr_chardev}"])
         qemuargs.extend(["-device", arch.virtio_dev_type("serial")])
         qemuargs.extend(["-device", "virtserialport,name=virtme.stderr,chardev=stderr"])
 
-        qemuargs.extend(["-chardev", "file,id=dev_stdout,path=/proc/self/fd/1"])
+        qemuargs.extend(["-chardev", f"file,id=dev_stdout,{stdout_chardev}"])
         qemuargs.extend(["-device", arch.virtio_dev_type("serial")])
         qemuargs.extend(
             ["-device", "virtserialport,name=virtme.dev_stdout,chardev=dev_stdout"]
         )
 
-        qemuargs.extend(["-chardev", "file,id=dev_stderr,path=/proc/self/fd/2"])
+        qemuargs.extend(["-chardev", f"file,id=dev_stderr,{stderr_chardev}"])
         qemuargs.extend(["-device", arch.virtio_dev_type("serial")])
         qemuargs.extend(
             ["-device", "virtserialport,name=virtme.dev_stderr,chardev=dev_stderr"]
@@ -1222,6 +1467,18 @@ def do_it() -> int:
             qemuargs.extend(["-device", arch.virtio_dev_type("serial")])
             qemuargs.extend(["-device", "virtserialport,name=virtme.ret,chardev=ret"])
 
+        # If stdin is a terminal, propagate its size to the script: the initial
+        # size is passed on the kernel cmdline, the following changes through
+        # a dedicated virtio serial port.
+        if os.isatty(0):
+            terminal_size = os.get_terminal_size(0)
+            kernelargs.append(f"virtme.winsize={terminal_size.lines}x{terminal_size.columns}")
+            winsize_path = os.path.join(tempfile.mkdtemp(prefix="virtme_winsize"), "sock")
+            atexit.register(cleanup_winsize)
+            qemuargs.extend(["-chardev", f"socket,id=winsize,path={winsize_path},server=on,wait=off"])
+            qemuargs.extend(["-device", arch.virtio_dev_type("serial")])
+            qemuargs.extend(["-device", "virtserialport,name=virtme.winsize,chardev=winsize"])
+
         # Scripts shouldn't reboot and shouldn't hang on panic: make sure to
         # force an exit condition if a panic happens.
         qemuargs.extend(["-no-reboot"])
@@ -1274,6 +1531,15 @@ def do_it() -> int:
         if video_args:
             qemuargs.extend(video_args)
 
+        # Share the clipboard using the spice agent protocol, implemented by
+        # QEMU on the host side (qemu-vdagent) and by spice-vdagent in the guest.
+        if args.clipboard:
+            qemuargs.extend(["-chardev", "qemu-vdagent,id=vdagent,name=vdagent,clipboard=on"])
+            qemuargs.extend(["-device", arch.virtio_dev_type("serial")])
+            qemuargs.extend(["-device", "virtserialport,name=com.redhat.spice.0,chardev=vdagent"])
+    elif args.clipboard:
+        sys.stderr.write("virtme: --clipboard requires --graphics, ignoring it\n")
+
     if args.net:
         extend_dhcp = False
         index = 0
@@ -1323,6 +1589,8 @@ def do_it() -> int:
     if args.user:
         kernelargs.append("virtme_user=%s" % args.user)
 
+    kernelargs.extend(env_kernel_args(args.env))
+
     # If we are running as root on the host pass this information to the guest
     # (this can be useful to properly support running virtme-ng instances
     # inside docker)
@@ -1436,8 +1704,27 @@ def do_it() -> int:
     if not args.dry_run:
         pid = os.fork()
         if pid:
+            if winsize_path is not None:
+                winsize_sock = None
+
+                def forward_winsize(_signum, _frame):
+                    nonlocal winsize_sock
+                    terminal_size = os.get_terminal_size(0)
+                    try:
+                        if winsize_sock is None:
+                            winsize_sock = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+                            winsize_sock.connect(winsize_path)
+                        winsize_sock.sendall(f"{terminal_size.lines} {terminal_size.columns}\n".encode())
+                    except OSError:
+                        winsize_sock = None
+
+                signal.signal(signal.SIGWINCH, forward_winsize)
+            if push_path is not None:
+                threading.Thread(target=push_files, args=(push_path, args.push), daemon=True).start()
             try:
                 pid, status = os.waitpid(pid, 0)
+                if artifacts_path is not None:
+                    fetch_artifacts(artifacts_path, args.artifacts)
                 ret = fetch_script_retcode()
                 if ret is not None:
                     return ret
@@ -1455,6 +1742,58 @@ def do_it() -> int:
     return 0
 
 
+def push_files(sock_path: str, path: str) -> None:
+    # The guest reads the size of the archive, followed by the archive itself
+    try:
+        with tempfile.TemporaryFile() as archive:
+            with tarfile.open(fileobj=archive, mode="w") as tar:
+                tar.add(path, arcname=os.path.basename(os.path.normpath(path)))
+            size = archive.tell()
+            archive.seek(0)
+            with socket.socket(socket.AF_UNIX, socket.SOCK_STREAM) as sock:
+                # Wait for QEMU to create the socket
+                for _ in range(100):
+                    try:
+                        sock.connect(sock_path)
+                        break
+                    except (FileNotFoundError, ConnectionRefusedError):
+                        sleep(0.1)
+                else:
+                    raise OSError(f"can't connect to {sock_path}")
+                sock.sendall(f"{size}\n".encode())
+                sock.sendfile(archive)
+    except (tarfile.TarError, OSError) as exc:
+        sys.stderr.write(f"virtme: failed to push {path}: {exc}\n")
+
+
+def check_artifacts(tar: tarfile.TarFile, dest: str) -> None:
+    # Only regular files and directories, that stay inside dest, are allowed
+    # (what the "data" extraction filter enforces, when available)
+    dest = os.path.realpath(dest)
+    for member in tar.getmembers():
+        if not (member.isfile() or member.isdir()):
+            raise tarfile.TarError(f"{member.name}: not a regular file or directory")
+        target = os.path.realpath(os.path.join(dest, member.name))
+        if os.path.commonpath([dest, target]) != dest:
+            raise tarfile.TarError(f"{member.name}: outside of {dest}")
+        member.mode &= 0o755
+
+
+def fetch_artifacts(path: str, dest: str) -> None:
+    if os.path.getsize(path) == 0:
+        return
+    os.makedirs(dest, exist_ok=True)
+    try:
+        with tarfile.open(path) as tar:
+            if hasattr(tarfile, "data_filter"):
+                tar.extractall(dest, filter="data")
+            else:
+                check_artifacts(tar, dest)
+                tar.extractall(dest)
+    except (tarfile.TarError, OSError) as exc:
+        sys.stderr.write(f"virtme: failed to retrieve the artifacts: {exc}\n")
+
+
 def save_terminal_settings():
     return termios.tcgetattr(sys.stdin) if sys.stdin.isatty() else None
 
@@ -164,7 +164,9 @@ virtme-ng is based on virtme, written by Andy Lutomirski <luto@kernel.org>.
     parser.add_argument(
         "--no-virtme-ng-init",
         action="store_true",
-        help="Fallback to the bash virtme-init (useful for debugging/development)",
+        help="Fallback to the bash virtme-init (useful for debugging/development). "
+        + "The bash virtme-init is always used with the options that only it supports "
+        + "(e.g., --env, --result, --control, --notify, --artifacts, --push, virtme.* guest parameters)",
     )
 
     parser.add_argument(
@@ -177,6 +179,69 @@ virtme-ng is based on virtme, written by Andy Lutomirski <luto@kernel.org>.
         "--snaps", action="store_true", help="Allow to execute snaps inside virtme-ng"
     )
 
+    parser.add_argument(
+        "--result",
+        action="store",
+        metavar="PATH",
+        help="Write a JSON report of the script execution (exit code, timings, resource usage) to PATH",
+    )
+
+    parser.add_argument(
+        "--control",
+        action="store",
+        metavar="PATH",
+        help="Create a control socket at PATH to send commands to the guest (rerun, freeze, thaw, poweroff)",
+    )
+
+    parser.add_argument(
+        "--script-stdout",
+        action="store",
+        metavar="PATH",
+        help="Write the standard output of the script to PATH instead of stdout",
+    )
+
+    parser.add_argument(
+        "--script-stderr",
+        action="store",
+        metavar="PATH",
+        help="Write the standard error of the script to PATH instead of stderr",
+    )
+
+    parser.add_argument(
+        "--kmsg",
+        action="store",
+        metavar="PATH",
+        help="Write the kernel log records of the guest (/dev/kmsg format) to PATH",
+    )
+
+    parser.add_argument(
+        "--qga",
+        action="store",
+        metavar="PATH",
+        help="Create a QEMU guest agent socket at PATH (org.qemu.guest_agent.0 channel)",
+    )
+
+    parser.add_argument(
+        "--artifacts",
+        action="store",
+        metavar="DIR",
+        help="Retrieve the content of the guest workspace ($VIRTME_WORKSPACE) into DIR at shutdown",
+    )
+
+    parser.add_argument(
+        "--push",
+        action="store",
+        metavar="PATH",
+        help="Copy the file or directory PATH into the guest workspace ($VIRTME_WORKSPACE) at boot",
+    )
+
+    parser.add_argument(
+        "--notify",
+        action="store",
+        metavar="PATH",
+        help="Write the readiness notifications (sd_notify) of the guest to PATH",
+    )
+
     parser.add_argument(
         "--debug",
         action="store_true",
@@ -249,6 +314,15 @@ virtme-ng is based on virtme, written by Andy Lutomirski <luto@kernel.org>.
         help="Change user inside the guest (default is same user as the host)",
     )
 
+    parser.add_argument(
+        "--env",
+        action="append",
+        default=[],
+        metavar="NAME[=VALUE]",
+        help="Pass an environment variable to the guest "
+        + "(the value is taken from the host if not specified)",
+    )
+
     parser.add_argument(
         "--root",
         action="store",
@@ -346,6 +420,20 @@ virtme-ng is based on virtme, written by Andy Lutomirski <luto@kernel.org>.
         help="Allow the host to ask the guest to release memory",
     )
 
+    parser.add_argument(
+        "--vsock-cid",
+        action="store",
+        type=int,
+        metavar="CID",
+        help="Add a vsock device with the context ID CID (>= 3), required by the guest vsock services",
+    )
+
+    parser.add_argument(
+        "--rng",
+        action="store_true",
+        help="Add a virtio-rng device to seed the guest RNG early",
+    )
+
     parser.add_argument(
         "--network",
         "-n",
@@ -395,6 +483,12 @@ virtme-ng is based on virtme, written by Andy Lutomirski <luto@kernel.org>.
         help="Show graphical output instead of using a console.",
     )
 
+    parser.add_argument(
+        "--clipboard",
+        action="store_true",
+        help="Share the clipboard with the guest in graphics mode (requires spice-vdagent in the guest)",
+    )
+
     parser.add_argument(
         "--verbose",
         "-v",
@@ -789,6 +883,11 @@ class KernelSource:
             else:
                 self.virtme_param["user"] = ""
 
+    def _get_virtme_env(self, args):
+        self.virtme_param["env"] = ""
+        for item in args.env:
+            self.virtme_param["env"] += f'--env "{item}" '
+
     def _get_virtme_arch(self, args):
         if args.arch is not None:
             if args.arch not in ARCH_MAPPING:
@@ -935,6 +1034,8 @@ class KernelSource:
     def _get_virtme_graphics(self, args):
         if args.graphics:
             self.virtme_param["graphics"] = '--graphics'
+            if args.clipboard:
+                self.virtme_param["graphics"] += ' --clipboard'
         else:
             self.virtme_param["graphics"] = ""
 
@@ -974,6 +1075,18 @@ class KernelSource:
         else:
             self.virtme_param["balloon"] = ""
 
+    def _get_virtme_vsock_cid(self, args):
+        if args.vsock_cid is not None:
+            self.virtme_param["vsock_cid"] = f"--vsock-cid {args.vsock_cid}"
+        else:
+            self.virtme_param["vsock_cid"] = ""
+
+    def _get_virtme_rng(self, args):
+        if args.rng:
+            self.virtme_param["rng"] = "--rng"
+        else:
+            self.virtme_param["rng"] = ""
+
     def _get_virtme_gdb(self, args):
         if args.gdb:
             def signal_handler(_signum, _frame):
@@ -989,6 +1102,52 @@ class KernelSource:
         else:
             self.virtme_param["snaps"] = ""
 
+    def _get_virtme_result(self, args):
+        if args.result is not None:
+            self.virtme_param["result"] = "--result " + args.result
+        else:
+            self.virtme_param["result"] = ""
+
+    def _get_virtme_control(self, args):
+        if args.control is not None:
+            self.virtme_param["control"] = "--control " + args.control
+        else:
+            self.virtme_param["control"] = ""
+
+    def _get_virtme_script_output(self, args):
+        script_output = ""
+        if args.script_stdout is not None:
+            script_output += "--script-stdout " + args.script_stdout + " "
+        if args.script_stderr is not None:
+            script_output += "--script-stderr " + args.script_stderr + " "
+        if args.kmsg is not None:
+            script_output += "--kmsg " + args.kmsg
+        self.virtme_param["script_output"] = script_output
+
+    def _get_virtme_qga(self, args):
+        if args.qga is not None:
+            self.virtme_param["qga"] = "--qga " + args.qga
+        else:
+            self.virtme_param["qga"] = ""
+
+    def _get_virtme_artifacts(self, args):
+        if args.artifacts is not None:
+            self.virtme_param["artifacts"] = "--artifacts " + args.artifacts
+        else:
+            self.virtme_param["artifacts"] = ""
+
+    def _get_virtme_push(self, args):
+        if args.push is not None:
+            self.virtme_param["push"] = "--push " + args.push
+        else:
+            self.virtme_param["push"] = ""
+
+    def _get_virtme_notify(self, args):
+        if args.notify is not None:
+            self.virtme_param["notify"] = "--notify " + args.notify
+        else:
+            self.virtme_param["notify"] = ""
+
     def _get_virtme_busybox(self, args):
         if args.busybox is not None:
             self.virtme_param["busybox"] = "--busybox " + args.busybox
@@ -1027,6 +1186,7 @@ class KernelSource:
         self._get_virtme_name(args)
         self._get_virtme_exec(args)
         self._get_virtme_user(args)
+        self._get_virtme_env(args)
         self._get_virtme_arch(args)
         self._get_virtme_root(args)
         self._get_virtme_rw(args)
@@ -1052,8 +1212,17 @@ class KernelSource:
         self._get_virtme_memory(args)
         self._get_virtme_numa(args)
         self._get_virtme_balloon(args)
+        self._get_virtme_rng(args)
+        self._get_virtme_vsock_cid(args)
         self._get_virtme_gdb(args)
         self._get_virtme_snaps(args)
+        self._get_virtme_result(args)
+        self._get_virtme_control(args)
+        self._get_virtme_notify(args)
+        self._get_virtme_artifacts(args)
+        self._get_virtme_push(args)
+        self._get_virtme_qga(args)
+        self._get_virtme_script_output(args)
         self._get_virtme_busybox(args)
         self._get_virtme_qemu(args)
         self._get_virtme_qemu_opts(args)
@@ -1064,6 +1233,7 @@ class KernelSource:
             + f'{self.virtme_param["name"]} '
             + f'{self.virtme_param["exec"]} '
             + f'{self.virtme_param["user"]} '
+            + f'{self.virtme_param["env"]} '
             + f'{self.virtme_param["arch"]} '
             + f'{self.virtme_param["root"]} '
             + f'{self.virtme_param["rw"]} '
@@ -1089,8 +1259,17 @@ class KernelSource:
             + f'{self.virtme_param["memory"]} '
             + f'{self.virtme_param["numa"]} '
             + f'{self.virtme_param["balloon"]} '
+            + f'{self.virtme_param["rng"]} '
+            + f'{self.virtme_param["vsock_cid"]} '
             + f'{self.virtme_param["gdb"]} '
             + f'{self.virtme_param["snaps"]} '
+            + f'{self.virtme_param["result"]} '
+            + f'{self.virtme_param["control"]} '
+            + f'{self.virtme_param["notify"]} '
+            + f'{self.virtme_param["artifacts"]} '
+            + f'{self.virtme_param["push"]} '
+            + f'{self.virtme_param["qga"]} '
+            + f'{self.virtme_param["script_output"]} '
             + f'{self.virtme_param["busybox"]} '
             + f'{self.virtme_param["qemu"]} '
             + f'{self.virtme_param["qemu_opts"]} '